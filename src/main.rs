@@ -4,10 +4,12 @@
 use std::time::Instant;
 
 use egui::{Align2, Color32, ComboBox, DragValue, ScrollArea, Stroke, Ui, WidgetText};
-use quantum::{b_field, spin_expectation, Complex, SpinState, SZ_POSITIVE_STATE};
-use threegui::{utils, ThreeUi, Vec3};
+use quantum::{Complex, FieldDrive, PidController, SpinState, Vector3, Waveform, SZ_POSITIVE_STATE};
+use script::FieldScript;
+use threegui::{ThreeUi, Vec3};
 
 mod quantum;
+mod script;
 
 fn is_mobile(ctx: &egui::Context) -> bool {
     use egui::os::OperatingSystem;
@@ -57,16 +59,65 @@ fn main() {
     });
 }
 
+/// How many integration steps pass between renormalizations of `state`.
+const RENORMALIZE_EVERY: u32 = 32;
+
+/// How far the orbit camera's elevation may tilt from the equator before it would flip
+/// over a pole.
+const ORBIT_ELEVATION_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.05;
+
+const ORBIT_DRAG_SPEED: f32 = 0.01;
+const ORBIT_ZOOM_SPEED: f32 = 0.01;
+const ORBIT_MIN_DISTANCE: f32 = 1.0;
+const ORBIT_MAX_DISTANCE: f32 = 50.0;
+
+/// Anti-windup clamp applied to each component of the PID controller's integral term.
+const CONTROL_INTEGRAL_LIMIT: f32 = 5.0;
+
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct TemplateApp {
     theta: f32,
+    #[serde(with = "quantum::serde_spin_state")]
     initial_state: SpinState,
+    bloch_theta: f32,
+    bloch_phi: f32,
+    #[serde(skip, default = "quantum::sz_positive_state")]
+    state: SpinState,
+    #[serde(skip)]
+    steps_since_renorm: u32,
     b_field_strength: f32,
     time: f32,
     play: bool,
     anim_speed: f32,
 
+    drive_enabled: bool,
+    drive_waveform: Waveform,
+    drive_amplitude: f32,
+    drive_frequency: f32,
+    drive_phase: f32,
+
+    cam_azimuth: f32,
+    cam_elevation: f32,
+    cam_distance: f32,
+
+    control_enabled: bool,
+    #[serde(with = "quantum::serde_vector3")]
+    control_target: Vector3,
+    control_kp: f32,
+    control_ki: f32,
+    control_kd: f32,
+    #[serde(skip)]
+    pid: PidController,
+
+    script_enabled: bool,
+    script_source: String,
+    #[serde(skip)]
+    field_script: FieldScript,
+
     trace: bool,
+    #[serde(skip)]
     tracing: Vec<Vec3>,
     max_trace_points: usize,
 
@@ -80,11 +131,36 @@ impl Default for TemplateApp {
             b_field_strength: 0.9,
             theta: 0.17,
             initial_state: quantum::SZ_POSITIVE_STATE,
+            bloch_theta: 0.,
+            bloch_phi: 0.,
+            state: quantum::SZ_POSITIVE_STATE,
+            steps_since_renorm: 0,
             time: 0.,
 
             play: true,
             anim_speed: 1.,
 
+            drive_enabled: false,
+            drive_waveform: Waveform::Sine,
+            drive_amplitude: 0.5,
+            drive_frequency: 1.0,
+            drive_phase: 0.,
+
+            cam_azimuth: std::f32::consts::FRAC_PI_4,
+            cam_elevation: std::f32::consts::FRAC_PI_6,
+            cam_distance: 5.0,
+
+            control_enabled: false,
+            control_target: Vector3::new(0., 0., 1.),
+            control_kp: 1.0,
+            control_ki: 0.0,
+            control_kd: 0.0,
+            pid: PidController::new(1.0, 0.0, 0.0),
+
+            script_enabled: false,
+            script_source: script::DEFAULT_SOURCE.to_owned(),
+            field_script: FieldScript::default(),
+
             trace: true,
             tracing: vec![],
 
@@ -97,17 +173,183 @@ impl Default for TemplateApp {
 
 impl TemplateApp {
     /// Called once before the first frame.
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app: Self = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+
+        app.state = app.initial_state;
+        app.field_script.compile(&app.script_source);
+        app
+    }
+
+    /// Build `initial_state` from the `(bloch_theta, bloch_phi)` polar/azimuthal pair:
+    /// `a = cos(theta/2)`, `b = e^{i phi} sin(theta/2)`.
+    fn apply_bloch_angles(&mut self) {
+        let half = self.bloch_theta / 2.0;
+        self.initial_state = SpinState::new(
+            Complex::new(half.cos(), 0.0),
+            Complex::new(self.bloch_phi.cos(), self.bloch_phi.sin()) * half.sin(),
+        );
+    }
+
+    /// Recompute `(bloch_theta, bloch_phi)` to match `initial_state`, after a direct edit
+    /// of its amplitudes.
+    fn sync_bloch_angles(&mut self) {
+        let v = quantum::spin_expectation(self.initial_state);
+        let radius = v.norm();
+        if radius > f32::EPSILON {
+            self.bloch_theta = (v.z / radius).clamp(-1.0, 1.0).acos();
+            self.bloch_phi = v.y.atan2(v.x);
+        }
+    }
+
+    /// Snap the running simulation back to `initial_state`, clearing the renormalization
+    /// count and the PID history; `time` is left unchanged. Called whenever the initial
+    /// state is edited, so the UI doesn't keep propagating from a value the user just
+    /// replaced.
+    fn reset_to_initial_state(&mut self) {
+        self.state = self.initial_state;
+        self.steps_since_renorm = 0;
+        self.pid.reset();
+    }
+
+    /// The instantaneous `B(t)` driving the simulation: the user's script if enabled and
+    /// it evaluates without error, otherwise the built-in tilt + transverse drive.
+    fn b_field(&mut self) -> Vector3 {
+        if self.script_enabled {
+            if let Some(b) = self.field_script.eval(self.time) {
+                return b;
+            }
+        }
+        self.field_drive().b_at(self.time)
+    }
+
+    fn field_drive(&self) -> FieldDrive {
+        FieldDrive {
+            theta: self.theta,
+            b_field_strength: self.b_field_strength,
+            drive_enabled: self.drive_enabled,
+            drive_waveform: self.drive_waveform,
+            drive_amplitude: self.drive_amplitude,
+            drive_frequency: self.drive_frequency,
+            drive_phase: self.drive_phase,
+        }
+    }
+
+    /// Advance `state` by `dt` under the current `B(t)` (plus any feedback control field),
+    /// renormalizing periodically to curb drift.
+    fn step_state(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let mut b = self.b_field();
+        if self.control_enabled {
+            self.pid.kp = self.control_kp;
+            self.pid.ki = self.control_ki;
+            self.pid.kd = self.control_kd;
+
+            let error = self.control_target - quantum::spin_expectation(self.state);
+            b += self.pid.update(error, dt, CONTROL_INTEGRAL_LIMIT);
+        }
+
+        self.state = quantum::propagate_step(self.state, b, dt);
+
+        self.steps_since_renorm += 1;
+        if self.steps_since_renorm >= RENORMALIZE_EVERY {
+            self.state = quantum::renormalize(self.state);
+            self.steps_since_renorm = 0;
+        }
+    }
+
+    /// Update `cam_azimuth`/`cam_elevation` from pointer drag and `cam_distance` from
+    /// scroll, while the pointer is over `rect` (the 3D view).
+    ///
+    /// There's no pinned `threegui` version in this tree to confirm a `ThreeUi`-level
+    /// camera API against, so orbiting is implemented entirely with plain `egui::Context`
+    /// input instead of relying on one; see [`Self::orbit_transform`].
+    fn handle_orbit_input(&mut self, ctx: &egui::Context, rect: egui::Rect) {
+        ctx.input(|i| {
+            let hovered = i.pointer.latest_pos().is_some_and(|pos| rect.contains(pos));
+            if hovered && i.pointer.primary_down() {
+                let drag = i.pointer.delta();
+                self.cam_azimuth -= drag.x * ORBIT_DRAG_SPEED;
+                self.cam_elevation = (self.cam_elevation + drag.y * ORBIT_DRAG_SPEED)
+                    .clamp(-ORBIT_ELEVATION_LIMIT, ORBIT_ELEVATION_LIMIT);
+            }
+
+            if hovered {
+                let scroll = i.smooth_scroll_delta.y;
+                self.cam_distance = (self.cam_distance - scroll * ORBIT_ZOOM_SPEED)
+                    .clamp(ORBIT_MIN_DISTANCE, ORBIT_MAX_DISTANCE);
+            }
+        });
+    }
+
+    /// Rotate and scale `v` to emulate orbiting/zooming the camera, relative to the
+    /// default view (so it's the identity transform at default camera settings). Applied
+    /// to *every* vector drawn in `ui_3d`, including the grid, since we can't verify a
+    /// `ThreeUi` camera API to apply it the other way around.
+    ///
+    /// Re-expresses `v`'s coordinates in the current camera's basis using the default
+    /// camera's basis vectors, which is the standard trick for emulating a moving camera
+    /// by rotating the scene instead: rendered through `threegui`'s untouched default
+    /// camera, the result looks the same as `v` would look through the current one.
+    fn orbit_transform(&self, v: Vec3) -> Vec3 {
+        let default = Self::default();
+        let basis_default = camera_basis(spherical_eye(default.cam_azimuth, default.cam_elevation, 1.0));
+        let basis_current = camera_basis(spherical_eye(self.cam_azimuth, self.cam_elevation, 1.0));
+
+        let v = basis_default.0 * dot(v, basis_current.0)
+            + basis_default.1 * dot(v, basis_current.1)
+            + basis_default.2 * dot(v, basis_current.2);
+
+        v * (default.cam_distance / self.cam_distance.max(f32::EPSILON))
     }
 }
 
-fn edit_complex(ui: &mut Ui, cpx: &mut Complex, name: &str, speed: f32) {
+/// Position of an orbit camera's eye, in world space around the origin.
+fn spherical_eye(azimuth: f32, elevation: f32, distance: f32) -> Vec3 {
+    let (sin_az, cos_az) = azimuth.sin_cos();
+    let (sin_el, cos_el) = elevation.sin_cos();
+    Vec3::new(
+        distance * cos_el * cos_az,
+        distance * sin_el,
+        distance * cos_el * sin_az,
+    )
+}
+
+/// `(right, up, forward)` basis of a camera sitting at `eye`, looking at the origin with
+/// world-up `Vec3::Y` as a hint.
+fn camera_basis(eye: Vec3) -> (Vec3, Vec3, Vec3) {
+    let forward = (Vec3::ZERO - eye).normalize();
+    let right = cross(forward, Vec3::Y).normalize();
+    let up = cross(right, forward);
+    (right, up, forward)
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    Vec3::new(
+        a.y * b.z - a.z * b.y,
+        a.z * b.x - a.x * b.z,
+        a.x * b.y - a.y * b.x,
+    )
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+fn edit_complex(ui: &mut Ui, cpx: &mut Complex, name: &str, speed: f32) -> egui::Response {
     ui.horizontal(|ui| {
         ui.label(name);
-        ui.add(DragValue::new(&mut cpx.re).prefix("Re: ").speed(speed));
-        ui.add(DragValue::new(&mut cpx.im).prefix("Im: ").speed(speed));
-    });
+        let re = ui.add(DragValue::new(&mut cpx.re).prefix("Re: ").speed(speed));
+        let im = ui.add(DragValue::new(&mut cpx.im).prefix("Im: ").speed(speed));
+        re | im
+    })
+    .inner
 }
 
 impl eframe::App for TemplateApp {
@@ -121,16 +363,11 @@ impl eframe::App for TemplateApp {
             } else {
                 self.time += delta;
             }
+            self.step_state(delta);
         }
 
         if self.trace {
-            let spin_vector: mint::Vector3<f32> = spin_expectation(
-                self.theta,
-                self.initial_state,
-                self.b_field_strength,
-                self.time,
-            )
-            .into();
+            let spin_vector: mint::Vector3<f32> = quantum::spin_expectation(self.state).into();
 
             if self.tracing.len() > self.max_trace_points {
                 let idx = self
@@ -160,45 +397,81 @@ impl eframe::App for TemplateApp {
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.label("3D plot");
+            let view_rect = ui.available_rect_before_wrap();
+            self.handle_orbit_input(ctx, view_rect);
             threegui::threegui(ui, |three| self.ui_3d(three));
             if self.show_psi_plot {
                 self.plot_psi(ui);
             }
         });
     }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
 }
 
 impl TemplateApp {
     fn ui_3d(&mut self, three: &mut ThreeUi) {
-        // Draw grid
-        utils::grid(
-            three.painter(),
+        // Draw grid, transformed along with everything else so it orbits in step with
+        // the axes and spin vector instead of sitting still as a stale "up" reference
+        draw_grid(
+            three,
             10,
             1.,
             Stroke::new(1.0, Color32::from_gray(45)),
+            |v| self.orbit_transform(v),
         );
 
         // Draw axes
-        axes(three);
+        axes(three, |v| self.orbit_transform(v));
 
         // Draw B field
-        let b_field: mint::Vector3<f32> = b_field(self.theta, self.b_field_strength).into();
-        label_line(three, b_field.into(), Color32::from_rgb(222, 230, 44), "B");
+        let b_field: mint::Vector3<f32> = self.b_field().into();
+        label_line(
+            three,
+            self.orbit_transform(b_field.into()),
+            Color32::from_rgb(222, 230, 44),
+            "B",
+        );
 
         // Draw spin vector
-        let spin_vector: mint::Vector3<f32> = spin_expectation(
-            self.theta,
-            self.initial_state,
-            self.b_field_strength,
-            self.time,
-        )
-        .into();
-        label_line(three, spin_vector.into(), Color32::LIGHT_BLUE, "<S>");
+        let spin_vector: mint::Vector3<f32> = quantum::spin_expectation(self.state).into();
+        label_line(
+            three,
+            self.orbit_transform(spin_vector.into()),
+            Color32::LIGHT_BLUE,
+            "<S>",
+        );
+
+        // Draw chosen initial state
+        let initial: mint::Vector3<f32> = quantum::spin_expectation(self.initial_state).into();
+        label_line(
+            three,
+            self.orbit_transform(initial.into()),
+            Color32::from_rgb(200, 120, 220),
+            "init",
+        );
+
+        // Draw control target
+        if self.control_enabled {
+            let target: mint::Vector3<f32> = self.control_target.into();
+            label_line(
+                three,
+                self.orbit_transform(target.into()),
+                Color32::from_rgb(230, 160, 20),
+                "target",
+            );
+        }
 
         // Draw tracing
         let paint = three.painter();
         for pair in self.tracing.windows(2) {
-            paint.line(pair[0], pair[1], Stroke::new(1., Color32::LIGHT_BLUE));
+            paint.line(
+                self.orbit_transform(pair[0]),
+                self.orbit_transform(pair[1]),
+                Stroke::new(1., Color32::LIGHT_BLUE),
+            );
         }
 
         /*
@@ -213,12 +486,7 @@ impl TemplateApp {
     }
 
     fn psi(&self) -> SpinState {
-        quantum::psi(
-            self.theta,
-            self.initial_state,
-            self.b_field_strength,
-            self.time,
-        )
+        self.state
     }
 
     fn plot_psi(&mut self, ui: &mut Ui) {
@@ -272,12 +540,107 @@ impl TemplateApp {
                 .speed(1e-2),
         );
 
-        /*
+        ui.separator();
         ui.strong("Initial state");
         let speed = 1e-2;
-        edit_complex(ui, &mut self.initial_state.x, "a: ", speed);
-        edit_complex(ui, &mut self.initial_state.y, "b: ", speed);
-        */
+        let mut amplitude_changed = false;
+        amplitude_changed |= edit_complex(ui, &mut self.initial_state.x, "a: ", speed).changed();
+        amplitude_changed |= edit_complex(ui, &mut self.initial_state.y, "b: ", speed).changed();
+        if ui.button("Normalize").clicked() {
+            let norm = self.initial_state.norm();
+            if norm > f32::EPSILON {
+                self.initial_state = self.initial_state.unscale(norm);
+            }
+            amplitude_changed = true;
+        }
+        if amplitude_changed {
+            self.sync_bloch_angles();
+            self.reset_to_initial_state();
+        }
+
+        ui.horizontal(|ui| {
+            let theta = ui.add(
+                DragValue::new(&mut self.bloch_theta)
+                    .prefix("θ_bloch: ")
+                    .suffix(" rads")
+                    .speed(1e-2),
+            );
+            let phi = ui.add(
+                DragValue::new(&mut self.bloch_phi)
+                    .prefix("φ_bloch: ")
+                    .suffix(" rads")
+                    .speed(1e-2),
+            );
+            if theta.changed() || phi.changed() {
+                self.apply_bloch_angles();
+                self.reset_to_initial_state();
+            }
+        });
+
+        ui.separator();
+        ui.strong("Transverse drive");
+        ui.checkbox(&mut self.drive_enabled, "Enable drive");
+        ComboBox::from_label("Waveform")
+            .selected_text(format!("{:?}", self.drive_waveform))
+            .show_ui(ui, |ui| {
+                for waveform in [Waveform::Sine, Waveform::Square, Waveform::Triangle] {
+                    ui.selectable_value(
+                        &mut self.drive_waveform,
+                        waveform,
+                        format!("{waveform:?}"),
+                    );
+                }
+            });
+        ui.add(
+            DragValue::new(&mut self.drive_amplitude)
+                .prefix("Amplitude: ")
+                .speed(1e-2),
+        );
+        ui.add(
+            DragValue::new(&mut self.drive_frequency)
+                .prefix("Frequency: ")
+                .speed(1e-2),
+        );
+        ui.add(
+            DragValue::new(&mut self.drive_phase)
+                .prefix("Phase: ")
+                .suffix(" rads")
+                .speed(1e-2),
+        );
+
+        ui.separator();
+        ui.strong("Scripted field");
+        ui.checkbox(&mut self.script_enabled, "Enable script (overrides drive)");
+        ui.add(
+            egui::TextEdit::multiline(&mut self.script_source)
+                .code_editor()
+                .desired_rows(6),
+        );
+        if ui.button("Compile").clicked() {
+            self.field_script.compile(&self.script_source);
+        }
+        if let Some(error) = self.field_script.error() {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        ui.separator();
+        ui.strong("Feedback control");
+        ui.checkbox(&mut self.control_enabled, "Enable PID control");
+        ui.horizontal(|ui| {
+            ui.label("Target:");
+            ui.add(DragValue::new(&mut self.control_target.x).prefix("x: ").speed(1e-2));
+            ui.add(DragValue::new(&mut self.control_target.y).prefix("y: ").speed(1e-2));
+            ui.add(DragValue::new(&mut self.control_target.z).prefix("z: ").speed(1e-2));
+        });
+        if ui.button("Normalize target").clicked() {
+            let norm = self.control_target.norm();
+            if norm > f32::EPSILON {
+                self.control_target /= norm;
+            }
+        }
+        ui.add(DragValue::new(&mut self.control_kp).prefix("Kp: ").speed(1e-2));
+        ui.add(DragValue::new(&mut self.control_ki).prefix("Ki: ").speed(1e-2));
+        ui.add(DragValue::new(&mut self.control_kd).prefix("Kd: ").speed(1e-2));
 
         ui.separator();
         ui.strong("Animation");
@@ -302,6 +665,24 @@ impl TemplateApp {
         ui.checkbox(&mut self.trace, "Trace spin vector");
         ui.checkbox(&mut self.show_psi_plot, "Show complex plane");
 
+        ui.separator();
+        ui.strong("Camera");
+        ui.horizontal(|ui| {
+            if ui.button("Reset view").clicked() {
+                self.cam_azimuth = Self::default().cam_azimuth;
+                self.cam_elevation = Self::default().cam_elevation;
+                self.cam_distance = Self::default().cam_distance;
+            }
+            if ui.button("Look down +Z").clicked() {
+                self.cam_azimuth = std::f32::consts::FRAC_PI_2;
+                self.cam_elevation = 0.;
+            }
+            if ui.button("Look down +X").clicked() {
+                self.cam_azimuth = 0.;
+                self.cam_elevation = 0.;
+            }
+        });
+
         ui.separator();
         ui.strong("Shortcuts");
         ui.horizontal(|ui| {
@@ -329,6 +710,7 @@ impl TemplateApp {
         ui.horizontal(|ui| {
             if ui.button("Reset time").clicked() {
                 self.time = Self::default().time;
+                self.reset_to_initial_state();
             }
 
             if ui.button("Reset angle").clicked() {
@@ -352,15 +734,34 @@ impl TemplateApp {
         }
 
         ui.add(DragValue::new(&mut self.max_trace_points).prefix("Maximum traced points: "));
+    }
+}
 
-        // TODO: Normalize button
+/// Draw a `count` x `count` grid of unit cells spaced `spacing` apart in the XZ plane,
+/// passing every drawn point through `transform` (see `orbit_transform`) so it orbits
+/// along with the rest of the scene instead of staying fixed.
+fn draw_grid(three: &mut ThreeUi, count: i32, spacing: f32, stroke: Stroke, transform: impl Fn(Vec3) -> Vec3) {
+    let half = count as f32 * spacing / 2.0;
+    let paint = three.painter();
+    for i in 0..=count {
+        let offset = -half + i as f32 * spacing;
+        paint.line(
+            transform(Vec3::new(offset, 0.0, -half)),
+            transform(Vec3::new(offset, 0.0, half)),
+            stroke,
+        );
+        paint.line(
+            transform(Vec3::new(-half, 0.0, offset)),
+            transform(Vec3::new(half, 0.0, offset)),
+            stroke,
+        );
     }
 }
 
-fn axes(three: &mut ThreeUi) {
-    label_line(three, Vec3::X, Color32::from_rgb(236, 52, 28), "X");
-    label_line(three, Vec3::Y, Color32::from_rgb(85, 230, 33), "Y");
-    label_line(three, Vec3::Z, Color32::from_rgb(28, 112, 232), "Z");
+fn axes(three: &mut ThreeUi, transform: impl Fn(Vec3) -> Vec3) {
+    label_line(three, transform(Vec3::X), Color32::from_rgb(236, 52, 28), "X");
+    label_line(three, transform(Vec3::Y), Color32::from_rgb(85, 230, 33), "Y");
+    label_line(three, transform(Vec3::Z), Color32::from_rgb(28, 112, 232), "Z");
 }
 
 fn label_line(three: &mut ThreeUi, v: Vec3, color: Color32, name: &str) {
@@ -37,32 +37,144 @@ fn expectation(state: SpinState, op: Operator) -> f32 {
     (state.adjoint() * op * state).into_scalar().re
 }
 
-/// e^(it)
-fn expi(t: f32) -> Complex {
-    Complex::new(t.cos(), t.sin())
+/// Shape of the oscillating transverse drive added on top of a static tilt field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Triangle,
 }
 
-pub fn psi(theta: f32, b_field_strength: f32, time: f32) -> SpinState {
-    // Magnitude of energy (same for both states)
-    let energy = b_field_strength * H_BAR / 2.0;
-    let omega = energy / H_BAR;
+impl Waveform {
+    /// Sample this waveform at phase `x` (radians), returning a value in `[-1, 1]`.
+    fn sample(self, x: f32) -> f32 {
+        match self {
+            Waveform::Sine => x.sin(),
+            Waveform::Square => {
+                if x.sin() >= 0.0 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => std::f32::consts::FRAC_2_PI * x.sin().asin(),
+        }
+    }
+}
+
+/// A composable time-dependent field: a static tilt (`b_field`) plus an optional
+/// oscillating transverse drive on the Y axis, for modelling RF pulses.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldDrive {
+    pub theta: f32,
+    pub b_field_strength: f32,
+    pub drive_enabled: bool,
+    pub drive_waveform: Waveform,
+    pub drive_amplitude: f32,
+    pub drive_frequency: f32,
+    pub drive_phase: f32,
+}
+
+impl FieldDrive {
+    /// Evaluate `B(t)` for this drive at `time`.
+    pub fn b_at(&self, time: f32) -> Vector3 {
+        let mut b = b_field(self.theta, self.b_field_strength);
+        if self.drive_enabled {
+            let phase = self.drive_frequency * time + self.drive_phase;
+            b.y += self.drive_amplitude * self.drive_waveform.sample(phase);
+        }
+        b
+    }
+}
+
+/// Evolve `state` forward by `dt` under the (assumed constant-over-the-step) field `b`.
+///
+/// `H dt / H_BAR = beta * (n . sigma)` where `beta = |B| * dt / 2` and `n = B / |B|`, so
+/// the exact single-step unitary is `exp(-i H dt / H_BAR) = cos(beta) I - i sin(beta) (n . sigma)`.
+/// Chaining this call across small steps approximates the propagator for arbitrary `B(t)`.
+pub fn propagate_step(state: SpinState, b: Vector3, dt: f32) -> SpinState {
+    let magnitude = b.norm();
+    if magnitude < f32::EPSILON {
+        return state;
+    }
+
+    let beta = magnitude * dt / 2.0;
+    let n = b / magnitude;
+    let sigma_n =
+        SX_OPERATOR * Complex::from(n.x) + SY_OPERATOR * Complex::from(n.y) + SZ_OPERATOR * Complex::from(n.z);
 
-    // Energy eigenstates
-    let psi_1 = SpinState::new((theta.cos() + 1.0).into(), theta.sin().into());
-    let psi_2 = SpinState::new((theta.cos() - 1.0).into(), theta.sin().into());
+    let u = Operator::identity() * Complex::from(beta.cos()) - sigma_n * Complex::new(0.0, beta.sin());
+    u * state
+}
 
-    (psi_1 * expi(omega * time) - psi_2 * expi(-omega * time)) / Complex::from(2.)
+/// Rescale `state` back to unit norm, guarding against the zero vector.
+pub fn renormalize(state: SpinState) -> SpinState {
+    let norm = state.norm();
+    if norm > f32::EPSILON {
+        state.unscale(norm)
+    } else {
+        state
+    }
 }
 
-pub fn spin_expectation(theta: f32, b_field_strength: f32, time: f32) -> Vector3 {
-    let wave = psi(theta, b_field_strength, time);
+pub fn spin_expectation(state: SpinState) -> Vector3 {
     Vector3::new(
-        expectation(wave, SX_OPERATOR),
-        expectation(wave, SY_OPERATOR),
-        expectation(wave, SZ_OPERATOR),
+        expectation(state, SX_OPERATOR),
+        expectation(state, SY_OPERATOR),
+        expectation(state, SZ_OPERATOR),
     )
 }
 
+/// `SZ_POSITIVE_STATE` exposed as a zero-arg fn, for use as a serde `default = "..."`.
+pub fn sz_positive_state() -> SpinState {
+    SZ_POSITIVE_STATE
+}
+
+/// `SpinState`/`Complex` come from nalgebra and don't derive serde, so this mirrors a
+/// state as its two complex amplitudes in plain `[f32; 2]` form for round-tripping.
+pub mod serde_spin_state {
+    use super::{Complex, SpinState};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Mirror {
+        a: [f32; 2],
+        b: [f32; 2],
+    }
+
+    pub fn serialize<S: Serializer>(state: &SpinState, serializer: S) -> Result<S::Ok, S::Error> {
+        Mirror {
+            a: [state.x.re, state.x.im],
+            b: [state.y.re, state.y.im],
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SpinState, D::Error> {
+        let mirror = Mirror::deserialize(deserializer)?;
+        Ok(SpinState::new(
+            Complex::new(mirror.a[0], mirror.a[1]),
+            Complex::new(mirror.b[0], mirror.b[1]),
+        ))
+    }
+}
+
+/// `Vector3` comes from nalgebra and doesn't derive serde, so this mirrors one as a
+/// plain `[f32; 3]` for round-tripping.
+pub mod serde_vector3 {
+    use super::Vector3;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(v: &Vector3, serializer: S) -> Result<S::Ok, S::Error> {
+        [v.x, v.y, v.z].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vector3, D::Error> {
+        let [x, y, z] = <[f32; 3]>::deserialize(deserializer)?;
+        Ok(Vector3::new(x, y, z))
+    }
+}
+
 pub fn spin_expectation_analytical(theta: f32, b_field_strength: f32, time: f32) -> Vector3 {
     let energy = b_field_strength * H_BAR / 1.0;
     let omega = energy / H_BAR;
@@ -71,3 +183,57 @@ pub fn spin_expectation_analytical(theta: f32, b_field_strength: f32, time: f32)
 
     Vector3::new(x, 0., 0.)
 }
+
+/// A standard three-term (PID) controller over a 3-vector error signal, used to steer
+/// `<S>` toward a target direction by feeding its output into the field.
+#[derive(Debug, Clone, Copy)]
+pub struct PidController {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    integral: Vector3,
+    prev_error: Option<Vector3>,
+}
+
+impl Default for PidController {
+    fn default() -> Self {
+        Self::new(0.0, 0.0, 0.0)
+    }
+}
+
+impl PidController {
+    pub fn new(kp: f32, ki: f32, kd: f32) -> Self {
+        Self {
+            kp,
+            ki,
+            kd,
+            integral: Vector3::zeros(),
+            prev_error: None,
+        }
+    }
+
+    /// Compute `u = Kp*e + Ki*integral + Kd*(e - prev_e)/dt` for the given `error`,
+    /// accumulating `integral += error * dt` clamped componentwise to `integral_limit`
+    /// to curb windup.
+    pub fn update(&mut self, error: Vector3, dt: f32, integral_limit: f32) -> Vector3 {
+        if dt <= 0.0 {
+            return Vector3::zeros();
+        }
+
+        self.integral = (self.integral + error * dt).map(|v| v.clamp(-integral_limit, integral_limit));
+
+        let derivative = match self.prev_error {
+            Some(prev) => (error - prev) / dt,
+            None => Vector3::zeros(),
+        };
+        self.prev_error = Some(error);
+
+        self.kp * error + self.ki * self.integral + self.kd * derivative
+    }
+
+    /// Clear the accumulated integral and derivative history.
+    pub fn reset(&mut self) {
+        self.integral = Vector3::zeros();
+        self.prev_error = None;
+    }
+}
@@ -0,0 +1,85 @@
+use rhai::{Array, Engine, Scope, AST};
+
+use crate::quantum::Vector3;
+
+/// Default program for a freshly created [`FieldScript`]: a static unit field along +Z
+/// (what [`crate::quantum::b_field`] gives for `theta = 0` and strength 1), expressed as a
+/// script so the text editor starts non-empty.
+pub const DEFAULT_SOURCE: &str = "fn b_field(t) {\n    [0.0, 0.0, 1.0]\n}\n";
+
+/// A user-authored `B(t)` program, compiled once via `rhai` and evaluated every step.
+///
+/// The script must define `fn b_field(t)` returning a 3-element array `[x, y, z]`.
+pub struct FieldScript {
+    engine: Engine,
+    ast: Option<AST>,
+    error: Option<String>,
+}
+
+impl Default for FieldScript {
+    fn default() -> Self {
+        Self {
+            engine: Engine::new(),
+            ast: None,
+            error: None,
+        }
+    }
+}
+
+impl FieldScript {
+    /// Compile `source`, caching the AST on success and recording the message on failure.
+    pub fn compile(&mut self, source: &str) {
+        match self.engine.compile(source) {
+            Ok(ast) => {
+                self.ast = Some(ast);
+                self.error = None;
+            }
+            Err(err) => {
+                self.ast = None;
+                self.error = Some(err.to_string());
+            }
+        }
+    }
+
+    /// The most recent compile or runtime error, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Call the compiled `b_field(t)` function. Returns `None` if nothing has compiled
+    /// (leaving `error` as it is), or if the call fails at runtime or does not return
+    /// three numbers (recording that error).
+    pub fn eval(&mut self, time: f32) -> Option<Vector3> {
+        let ast = self.ast.as_ref()?;
+        let result = self
+            .engine
+            .call_fn::<Array>(&mut Scope::new(), ast, "b_field", (time as f64,));
+
+        match result {
+            Ok(components) if components.len() == 3 => {
+                let mut xyz = [0.0f32; 3];
+                for (slot, value) in xyz.iter_mut().zip(components) {
+                    // rhai doesn't widen an integer literal (e.g. `0`) to FLOAT on its
+                    // own, so a script written with plain integers needs this fallback.
+                    match value.as_float().or_else(|_| value.as_int().map(|i| i as f64)) {
+                        Ok(f) => *slot = f as f32,
+                        Err(_) => {
+                            self.error = Some("b_field(t) must return an array of 3 numbers".into());
+                            return None;
+                        }
+                    }
+                }
+                self.error = None;
+                Some(Vector3::new(xyz[0], xyz[1], xyz[2]))
+            }
+            Ok(_) => {
+                self.error = Some("b_field(t) must return an array of 3 numbers".into());
+                None
+            }
+            Err(err) => {
+                self.error = Some(err.to_string());
+                None
+            }
+        }
+    }
+}